@@ -1,9 +1,12 @@
 use std::fs::File;
+use std::io::{self, Write};
 
 use camino::Utf8PathBuf;
-use miette::{IntoDiagnostic, WrapErr};
+use miette::{miette, IntoDiagnostic, WrapErr};
 use tracing::warn;
 
+use crate::installer;
+
 const GITHUB_CI_PART1: &str = r###"
 # CI that:
 #
@@ -25,170 +28,749 @@ permissions:
   contents: write
 
 # This task will run whenever you push a git tag that looks like
-# a version number. We just look for `v` followed by at least one number
-# and then whatever. so `v1`, `v1.0.0`, and `v1.0.0-prerelease` all work.
+# a version number, with or without a leading `v`, and with or without a
+# package-name prefix for cutting independent releases out of a workspace.
+# So `1.0.0`, `v0.1.0-prerelease.1`, `my-app/0.1.0`, and `releases/v1.0.0`
+# all trigger it (note that the glob below requires a literal `.` after the
+# leading digits, so a bare major version like `v1` will not trigger this).
+# Everything before the last `/` is taken as a package name, and the job
+# fails early unless that is a package in this workspace (so `releases/v1.0.0`
+# only goes through in a workspace with a package named `releases`).
 #
 # If there's a prerelease-style suffix to the version then the Github Release™️
 # will be marked as a prerelease (handled by taiki-e/create-gh-release-action).
 on:
   push:
     tags:
-      - v[0-9]+.*
+      - '**[0-9]+.*'
 
 env:"###;
 
-const GITHUB_CI_PART2: &str = r###"
+const GITHUB_CI_PART2A: &str = r###"
 jobs:
   # Create the Github Release™️ so the packages have something to be uploaded to
   create-release:
     runs-on: ubuntu-latest
     outputs:
       tag: ${{ steps.create-gh-release.outputs.computed-prefix }}${{ steps.create-gh-release.outputs.version }}
+      package-arg: ${{ steps.parse-tag.outputs.package-arg }}
     steps:
       - uses: actions/checkout@v3
+      - name: Parse package name and version out of the pushed tag
+        id: parse-tag
+        run: |
+          TAG="${GITHUB_REF#refs/tags/}"
+          if [[ "$TAG" == */* ]]; then
+            PACKAGE_NAME="${TAG%/*}"
+            VERSION="${TAG##*/}"
+          else
+            PACKAGE_NAME=""
+            VERSION="$TAG"
+          fi
+          VERSION="${VERSION#v}"
+          if ! [[ "$VERSION" =~ ^[0-9]+\.[0-9]+\.[0-9]+ ]]; then
+            echo "::error::tag '$TAG' doesn't contain a Cargo SemVer version (major.minor.patch)"
+            exit 1
+          fi
+          PACKAGE_ARG=""
+          if [ -n "$PACKAGE_NAME" ]; then"###;
+
+const GITHUB_CI_PART2A_TAIL: &str = r###"
+            PACKAGE_ARG="--package=$PACKAGE_NAME"
+          fi
+          echo "package-arg=$PACKAGE_ARG" >> "$GITHUB_OUTPUT"
       - id: create-gh-release
         uses: taiki-e/create-gh-release-action@v1
-        with:
-          # (optional) Path to changelog. This will used to for the body of the Github Releaase™️
-          # changelog: RELEASES.md
+        with:"###;
+
+const GITHUB_CI_PART2B: &str = r###"
           draft: true
           # (required) GitHub token for creating GitHub Releases.
           token: ${{ secrets.GITHUB_TOKEN }}
+"###;
 
+const NIGHTLY_PART1: &str = r###"
+# CI that builds unstable binaries off of `main` on a schedule (or on demand)
+# and publishes them to a rolling "nightly" prerelease, so users who want the
+# bleeding edge don't have to wait for a tagged release.
+name: Nightly
 
-  # Build and packages all the things
-  upload-artifacts:
-    needs: create-release
-    strategy:
-      matrix:
-        # For these target platforms
-        include:"###;
+permissions:
+  contents: write
 
-const GITHUB_CI_PART3: &str = r###"
-    runs-on: ${{ matrix.os }}
-    env:
-      GH_TOKEN: ${{ secrets.GITHUB_TOKEN }}
-    steps:
-      - uses: actions/checkout@v3
-      - name: Install Rust
-        run: rustup update stable && rustup default stable
-      - name: Install cargo-dist
-        # Currently we install cargo-dist from git, in the future when it's
-        # published on crates.io or has prebuilt binaries, we'll do better.
-        run: cargo install --git https://github.com/axodotdev/cargo-dist/
-      - name: Run cargo-dist
-        # This logic is a bit janky because it's trying to be a polyglot between
-        # powershell and bash since this will run on windows, macos, and linux!
-        # The two platforms don't agree on how to talk about env vars but they
-        # do agree on 'cat' and '$()' so we use that to marshal values between commmands.
-        run: |
-          cargo dist --output-format=json > dist-manifest.json
-          echo "dist ran successfully"
-          cat dist-manifest.json
-          cat dist-manifest.json | jq --raw-output ".releases[].artifacts[].path" > uploads.txt
-          echo "uploading..."
-          cat uploads.txt
-          gh release upload ${{ needs.create-release.outputs.tag }} $(cat uploads.txt)
-          echo "uploaded!"
-
-  # Compute and upload the manifest for everything
-  upload-manifest:
-    needs: create-release
-    runs-on: ubuntu-latest
-    env:
-      GH_TOKEN: ${{ secrets.GITHUB_TOKEN }}
-    steps:
-      - uses: actions/checkout@v3
-      - name: Install Rust
-        run: rustup update stable && rustup default stable
-      - name: Install cargo-dist
-        # Currently we install cargo-dist from git, in the future when it's
-        # published on crates.io or has prebuilt binaries, we'll do better.
-        run: cargo install --git https://github.com/axodotdev/cargo-dist/
-      - name: Run cargo-dist
-        run: |
-          cargo dist manifest --output-format=json $ALL_CARGO_DIST_TARGET_ARGS > dist-manifest.json
-          echo "dist ran successfully"
-          cat dist-manifest.json
-          gh release upload ${{ needs.create-release.outputs.tag }} dist-manifest.json
-          echo "uploaded!"
+on:
+  schedule:
+    # Every day at 00:00 UTC
+    - cron: '0 0 * * *'
+  workflow_dispatch:
 
+env:"###;
 
-  # Mark the Github Release™️ as a non-draft now that everything has succeeded!
-  publish-release:
-    needs: [create-release, upload-artifacts, upload-manifest]
+const NIGHTLY_PART2: &str = r###"
+jobs:
+  # (Re)create the rolling "nightly" prerelease so the packages have something to be uploaded to
+  create-release:
     runs-on: ubuntu-latest
+    outputs:
+      tag: nightly
     env:
       GH_TOKEN: ${{ secrets.GITHUB_TOKEN }}
     steps:
       - uses: actions/checkout@v3
-      - name: mark release as non-draft
+      - name: Compute nightly version
+        id: version
+        run: |
+          echo "version=0.0.0-nightly.$(date -u +%Y%m%d).$(git rev-parse --short HEAD)" >> "$GITHUB_OUTPUT"
+      - name: Delete previous nightly release
+        run: gh release delete nightly --yes --cleanup-tag || true
+      - name: Create nightly release
         run: |
-          gh release edit ${{ needs.create-release.outputs.tag }} --draft=false
+          gh release create nightly \
+            --title "Nightly (${{ steps.version.outputs.version }})" \
+            --notes "Unstable build off main@${{ github.sha }}. Not guaranteed to be stable, may be re-published at any time." \
+            --prerelease
 "###;
 
+/// Prefix of the marker line stamped into every file we generate, so a
+/// re-run can tell "ours, safe to clobber" apart from "hand-edited, don't
+/// touch" without a separate lockfile.
+const AUTOGEN_MARKER_PREFIX: &str = "# cargo-dist-hash:";
+
 pub fn generate_github_ci(
     workspace_dir: &Utf8PathBuf,
     targets: &[String],
+    packages: &[String],
+    allow_dirty: bool,
 ) -> Result<(), miette::Report> {
     const GITHUB_CI_DIR: &str = ".github/workflows/";
     const GITHUB_CI_FILE: &str = "release.yml";
 
-    // FIXME: should we try to avoid clobbering old files..?
     let ci_dir = workspace_dir.join(GITHUB_CI_DIR);
     let ci_file = ci_dir.join(GITHUB_CI_FILE);
     std::fs::create_dir_all(&ci_dir)
         .into_diagnostic()
         .wrap_err("Failed to create ci dir")?;
-    let mut file = File::create(ci_file)
+
+    // If the workspace has a changelog, point the release action at it so the
+    // Github Release™️ gets a real title/body instead of the default empty one.
+    // `create-gh-release-action` does its own Keep-a-Changelog style parsing
+    // and version-section slicing at release time, so we just need to hand it
+    // a path here rather than parse the file ourselves at generation time.
+    let changelog_path = discover_changelog(workspace_dir);
+
+    let hash = generator_input_hash(targets, packages, changelog_path.is_some());
+    let mut file = create_autogen_file(&ci_file, hash, allow_dirty)?;
+    write_autogen_header(&mut file, hash)
         .into_diagnostic()
-        .wrap_err("Failed to create ci file")?;
-    write_github_ci(&mut file, targets)
+        .wrap_err("Failed to write to CI file")?;
+
+    write_github_ci(&mut file, targets, packages, changelog_path.as_deref())
         .into_diagnostic()
         .wrap_err("Failed to write to CI file")?;
+
+    // Write the one-line installer scripts next to the workflow, so they can
+    // be uploaded as Release assets alongside the built archives.
+    installer::generate_install_scripts(&ci_dir, targets)
+        .wrap_err("Failed to generate installer scripts")?;
+
     Ok(())
 }
 
-fn write_github_ci(f: &mut File, targets: &[String]) -> Result<(), std::io::Error> {
-    use std::io::Write;
+/// Write a second workflow, `nightly.yml`, that builds unstable binaries off
+/// of `main` on a schedule (or on demand) instead of a tag push, reusing the
+/// same target matrix and artifact-upload machinery as the release workflow.
+pub fn generate_github_ci_nightly(
+    workspace_dir: &Utf8PathBuf,
+    targets: &[String],
+    allow_dirty: bool,
+) -> Result<(), miette::Report> {
+    const GITHUB_CI_DIR: &str = ".github/workflows/";
+    const GITHUB_CI_FILE: &str = "nightly.yml";
 
-    writeln!(f, "{GITHUB_CI_PART1}")?;
+    let ci_dir = workspace_dir.join(GITHUB_CI_DIR);
+    let ci_file = ci_dir.join(GITHUB_CI_FILE);
+    std::fs::create_dir_all(&ci_dir)
+        .into_diagnostic()
+        .wrap_err("Failed to create ci dir")?;
+
+    let hash = generator_input_hash(targets, &[], false);
+    let mut file = create_autogen_file(&ci_file, hash, allow_dirty)?;
+    write_autogen_header(&mut file, hash)
+        .into_diagnostic()
+        .wrap_err("Failed to write to nightly CI file")?;
 
-    // Write out target args
-    let mut target_args = Vec::new();
+    write_github_ci_nightly(&mut file, targets)
+        .into_diagnostic()
+        .wrap_err("Failed to write to nightly CI file")?;
+
+    Ok(())
+}
+
+/// Look for a `CHANGELOG.md` or `RELEASES.md` in `workspace_dir`.
+fn discover_changelog(workspace_dir: &Utf8PathBuf) -> Option<Utf8PathBuf> {
+    ["CHANGELOG.md", "RELEASES.md"]
+        .into_iter()
+        .map(|name| workspace_dir.join(name))
+        .find(|path| path.exists())
+}
+
+/// Hash the inputs that determine a generated workflow's contents, so a
+/// re-run can recognize its own output regardless of exact formatting.
+///
+/// This needs to be stable across Rust versions/builds for identical inputs,
+/// since it's persisted into a committed file and compared against on a
+/// later, possibly different, toolchain — unlike `std`'s `DefaultHasher`,
+/// whose algorithm is explicitly *not* guaranteed stable release to release.
+/// A plain FNV-1a over each input's bytes gives us that without pulling in a
+/// hashing crate for a non-cryptographic, not-attacker-facing marker.
+fn generator_input_hash(targets: &[String], packages: &[String], has_changelog: bool) -> u64 {
+    let mut hash = fnv1a(FNV_OFFSET_BASIS, env!("CARGO_PKG_VERSION").as_bytes());
+    hash = fnv1a(hash, &(targets.len() as u64).to_le_bytes());
     for target in targets {
-        write!(&mut target_args, "--target={target} ")?;
+        hash = fnv1a(hash, target.as_bytes());
     }
+    hash = fnv1a(hash, &(packages.len() as u64).to_le_bytes());
+    for package in packages {
+        hash = fnv1a(hash, package.as_bytes());
+    }
+    hash = fnv1a(hash, &[has_changelog as u8]);
+    hash
+}
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+
+/// One FNV-1a pass over `bytes` followed by a `0xff` terminator byte (which
+/// never occurs in UTF-8), continuing from `hash` (pass `FNV_OFFSET_BASIS`
+/// to start a new hash). The terminator keeps chained inputs from colliding
+/// with each other the way hashing their concatenation could (e.g. targets
+/// `["ab", "c"]` vs `["a", "bc"]`).
+fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes.iter().chain(&[0xff]) {
+        hash ^= u64::from(byte);
+        hash = hash.wrapping_mul(FNV_PRIME);
+    }
+    hash
+}
+
+fn write_autogen_header(f: &mut File, hash: u64) -> io::Result<()> {
+    writeln!(f, "# This file is autogenerated by cargo-dist.")?;
+    writeln!(f, "#")?;
+    writeln!(
+        f,
+        "# Rerunning \"cargo dist init\" always fully regenerates this file from scratch; it"
+    )?;
+    writeln!(
+        f,
+        "# does NOT merge or preserve hand edits. The line below is a hash of the inputs"
+    )?;
+    writeln!(
+        f,
+        "# (targets, packages, changelog presence, cargo-dist version), not of this file's"
+    )?;
     writeln!(
         f,
-        "  ALL_CARGO_DIST_TARGET_ARGS: {}",
-        String::from_utf8(target_args).unwrap()
+        "# contents: if it is intact and the inputs are unchanged, this file is overwritten"
+    )?;
+    writeln!(
+        f,
+        "# without asking, hand edits elsewhere in it included. If the inputs changed, or"
+    )?;
+    writeln!(
+        f,
+        "# that line is missing or altered, \"cargo dist init\" will refuse to touch it unless"
+    )?;
+    writeln!(f, "# --allow-dirty is passed, in which case it's overwritten wholesale.")?;
+    writeln!(f, "{AUTOGEN_MARKER_PREFIX}{hash:016x}")?;
+    Ok(())
+}
+
+/// Create (or overwrite) an autogenerated CI file at `path`, refusing to
+/// clobber an existing file that has no marker line for `hash` unless
+/// `allow_dirty` is set. Only the marker line is checked: edits elsewhere in
+/// a file whose marker matches are not detected and get overwritten.
+fn create_autogen_file(path: &Utf8PathBuf, hash: u64, allow_dirty: bool) -> Result<File, miette::Report> {
+    if let Ok(existing) = std::fs::read_to_string(path) {
+        let marker = format!("{AUTOGEN_MARKER_PREFIX}{hash:016x}");
+        let ours = existing.lines().any(|line| line == marker);
+        let stamped_at_all = existing.lines().any(|line| line.starts_with(AUTOGEN_MARKER_PREFIX));
+
+        if !ours && !allow_dirty {
+            return Err(miette!(
+                "refusing to overwrite {path}: {reason}\n\nRerun with --allow-dirty if you want \
+                 cargo-dist to clobber it anyway (diff it against the old file first!).",
+                reason = if stamped_at_all {
+                    "it was generated with different inputs (or an older cargo-dist)"
+                } else {
+                    "it doesn't look autogenerated by cargo-dist"
+                }
+            ));
+        }
+        if !ours {
+            warn!("overwriting {path} (no matching cargo-dist marker) because --allow-dirty was passed");
+        }
+    }
+
+    File::create(path)
+        .into_diagnostic()
+        .wrap_err_with(|| format!("Failed to create {path}"))
+}
+
+fn write_github_ci(
+    f: &mut File,
+    targets: &[String],
+    packages: &[String],
+    changelog_path: Option<&camino::Utf8Path>,
+) -> io::Result<()> {
+    writeln!(f, "{GITHUB_CI_PART1}")?;
+    writeln!(f, "  ALL_CARGO_DIST_TARGET_ARGS: {}", target_args(targets))?;
+
+    writeln!(f, "{GITHUB_CI_PART2A}")?;
+
+    // Validate that a tag like `my-app/1.0.0` names a real workspace package
+    // before we let it through to `cargo dist --package=...`.
+    write_package_validation_case(f, packages)?;
+
+    writeln!(f, "{GITHUB_CI_PART2A_TAIL}")?;
+
+    // (optional) Path to changelog. This will be used for the body of the Github Releaase™️.
+    // `create-gh-release-action` parses it Keep-a-Changelog style and slices out the section
+    // matching the pushed tag's version (falling back to `[Unreleased]` if nothing matches).
+    match changelog_path.and_then(|p| p.file_name()) {
+        Some(name) => writeln!(f, "          changelog: {name}")?,
+        None => writeln!(f, "          # changelog: RELEASES.md")?,
+    }
+
+    writeln!(f, "{GITHUB_CI_PART2B}")?;
+
+    write_build_job(
+        f,
+        "create-release",
+        targets,
+        Some("${{ needs.create-release.outputs.package-arg }}"),
     )?;
+    write_publish_job(
+        f,
+        "${{ needs.create-release.outputs.tag }}",
+        /* flip_draft */ true,
+        /* upload_installers */ true,
+    )?;
+
+    Ok(())
+}
+
+fn write_github_ci_nightly(f: &mut File, targets: &[String]) -> io::Result<()> {
+    writeln!(f, "{NIGHTLY_PART1}")?;
+    writeln!(f, "  ALL_CARGO_DIST_TARGET_ARGS: {}", target_args(targets))?;
+
+    writeln!(f, "{NIGHTLY_PART2}")?;
 
-    writeln!(f, "{GITHUB_CI_PART2}")?;
+    write_build_job(f, "create-release", targets, None)?;
+    write_publish_job(f, "nightly", /* flip_draft */ false, /* upload_installers */ false)?;
 
+    Ok(())
+}
+
+/// Write the `case "$PACKAGE_NAME" in ... esac` block that fails the workflow
+/// early with a clear error if a tag like `my-app/1.0.0` names a package that
+/// isn't actually in this workspace, rather than passing it straight through
+/// to `cargo dist --package=...` and getting a less obvious failure there.
+/// Generic over `Write` (rather than `&mut File` like its siblings) so it can
+/// be unit tested against an in-memory buffer.
+fn write_package_validation_case<W: Write>(f: &mut W, packages: &[String]) -> io::Result<()> {
+    writeln!(f, "            case \"$PACKAGE_NAME\" in")?;
+    if packages.is_empty() {
+        writeln!(f, "              *)")?;
+    } else {
+        writeln!(f, "              {})", packages.join("|"))?;
+        writeln!(f, "                ;;")?;
+        writeln!(f, "              *)")?;
+    }
+    writeln!(
+        f,
+        "                echo \"::error::'$PACKAGE_NAME' is not a package in this workspace\""
+    )?;
+    writeln!(f, "                exit 1")?;
+    writeln!(f, "                ;;")?;
+    writeln!(f, "            esac")?;
+    Ok(())
+}
+
+/// Compute the `--target=...` args cargo-dist invocations should be scoped to.
+fn target_args(targets: &[String]) -> String {
+    let mut target_args = String::new();
     for target in targets {
-        let Some(os) = github_os_for_target(target) else {
+        target_args.push_str(&format!("--target={target} "));
+    }
+    target_args
+}
+
+/// Write the `- target: ... \n  os: ... \n  cross: ... \n  rustup-target-add: ...`
+/// matrix entries shared by the release and nightly workflows, skipping (with
+/// a warning) any target we don't know how to map to a Github-hosted runner.
+///
+/// `cross`/`rustup-target-add` tell the build job how to actually produce a
+/// linked binary for that target, since not every triple in `targets` matches
+/// its runner's default host triple.
+fn write_target_matrix(f: &mut File, targets: &[String]) -> io::Result<()> {
+    for target in targets {
+        let Some(runner) = resolve_target_runner(target) else {
             warn!("skipping generating ci for {target} (no idea what github os should build this)");
             continue;
         };
         writeln!(f, "        - target: {target}")?;
-        writeln!(f, "          os: {os}")?;
+        writeln!(f, "          os: {}", runner.os)?;
+        writeln!(f, "          cross: {}", runner.strategy == BuildStrategy::Cross)?;
+        writeln!(
+            f,
+            "          rustup-target-add: {}",
+            runner.strategy == BuildStrategy::NativeCrossSdk
+        )?;
+    }
+    Ok(())
+}
+
+/// Write the `build` job: builds with cargo-dist on each target in the
+/// matrix, hashes the resulting artifacts, and stashes everything (plus a
+/// per-target manifest fragment) as a workflow artifact instead of uploading
+/// straight to the Release. `fail-fast: false` so one target's failure
+/// doesn't cancel the others, and a failed leg can be re-run on its own.
+/// Shared by the release and nightly workflows.
+fn write_build_job(
+    f: &mut File,
+    needs: &str,
+    targets: &[String],
+    package_arg_expr: Option<&str>,
+) -> io::Result<()> {
+    writeln!(f, "  # Build and package all the things")?;
+    writeln!(f, "  build:")?;
+    writeln!(f, "    needs: {needs}")?;
+    writeln!(f, "    strategy:")?;
+    writeln!(f, "      fail-fast: false")?;
+    writeln!(f, "      matrix:")?;
+    writeln!(f, "        # For these target platforms")?;
+    writeln!(f, "        include:")?;
+    write_target_matrix(f, targets)?;
+    writeln!(f, "    runs-on: ${{{{ matrix.os }}}}")?;
+    writeln!(f, "    env:")?;
+    writeln!(
+        f,
+        "      # `cross` wraps cargo for Linux triples the runner's toolchain can't link directly"
+    )?;
+    writeln!(f, "      # (different libc and/or arch than the host); everything else uses plain cargo.")?;
+    writeln!(f, "      CARGO: ${{{{ matrix.cross && 'cross' || 'cargo' }}}}")?;
+    if let Some(package_arg_expr) = package_arg_expr {
+        writeln!(f, "      PACKAGE_ARG: {package_arg_expr}")?;
     }
+    writeln!(f, "    steps:")?;
+    writeln!(f, "      - uses: actions/checkout@v3")?;
+    writeln!(f, "      - name: Install Rust")?;
+    writeln!(f, "        run: rustup update stable && rustup default stable")?;
+    writeln!(f, "      - name: Add rustup target")?;
+    writeln!(f, "        if: matrix.rustup-target-add")?;
+    writeln!(f, "        run: rustup target add ${{{{ matrix.target }}}}")?;
+    writeln!(f, "      - name: Install cross")?;
+    writeln!(
+        f,
+        "        # Provides a pre-built Docker cross-toolchain per target, so musl/non-native"
+    )?;
+    writeln!(f, "        # Linux arches can be linked without hand-rolling a toolchain here.")?;
+    writeln!(f, "        if: matrix.cross")?;
+    writeln!(f, "        run: cargo install cross --git https://github.com/cross-rs/cross")?;
+    writeln!(f, "      - name: Install cargo-dist")?;
+    writeln!(
+        f,
+        "        # Currently we install cargo-dist from git, in the future when it's"
+    )?;
+    writeln!(
+        f,
+        "        # published on crates.io or has prebuilt binaries, we'll do better."
+    )?;
+    writeln!(
+        f,
+        "        run: cargo install --git https://github.com/axodotdev/cargo-dist/"
+    )?;
+    writeln!(f, "      - name: Run cargo-dist")?;
+    writeln!(
+        f,
+        "        # Runs under bash on every runner (Git Bash on windows), so the script"
+    )?;
+    writeln!(
+        f,
+        "        # below is plain POSIX shell on windows, macos, and linux alike."
+    )?;
+    writeln!(f, "        shell: bash")?;
+    writeln!(f, "        run: |")?;
+    let package_arg = if package_arg_expr.is_some() { " $PACKAGE_ARG" } else { "" };
+    writeln!(
+        f,
+        "          $CARGO dist --target=${{{{ matrix.target }}}} --output-format=json{package_arg} > dist-manifest.json"
+    )?;
+    writeln!(f, "          echo \"dist ran successfully\"")?;
+    writeln!(f, "          cat dist-manifest.json")?;
+    writeln!(
+        f,
+        "          cat dist-manifest.json | jq --raw-output \".releases[].artifacts[].path\" > uploads.txt"
+    )?;
+    writeln!(f, "          echo \"hashing...\"")?;
+    writeln!(f, "          : > hashes.txt")?;
+    writeln!(f, "          while IFS= read -r artifact; do")?;
+    writeln!(
+        f,
+        "            # Hash by basename from inside the artifact's own dir, so the sidecar"
+    )?;
+    writeln!(
+        f,
+        "            # records the same flat filename the install script downloads later."
+    )?;
+    writeln!(f, "            if command -v sha256sum >/dev/null 2>&1; then")?;
+    writeln!(
+        f,
+        "              (cd \"$(dirname \"$artifact\")\" && sha256sum \"$(basename \"$artifact\")\") > \"$artifact.sha256\""
+    )?;
+    writeln!(f, "            else")?;
+    writeln!(
+        f,
+        "              (cd \"$(dirname \"$artifact\")\" && shasum -a 256 \"$(basename \"$artifact\")\") > \"$artifact.sha256\""
+    )?;
+    writeln!(f, "            fi")?;
+    writeln!(f, "            echo \"$artifact.sha256\" >> hashes.txt")?;
+    writeln!(f, "          done < uploads.txt")?;
+    writeln!(f, "          cat hashes.txt >> uploads.txt")?;
+    writeln!(f, "          mkdir -p fragment")?;
+    writeln!(f, "          cp $(cat uploads.txt) fragment/")?;
+    writeln!(f, "          cp dist-manifest.json \"fragment/manifest-${{{{ matrix.target }}}}.json\"")?;
+    writeln!(f, "      - name: Stash artifacts")?;
+    writeln!(f, "        uses: actions/upload-artifact@v4")?;
+    writeln!(f, "        with:")?;
+    writeln!(f, "          name: build-${{{{ matrix.target }}}}")?;
+    writeln!(f, "          path: fragment/*")?;
+
+    Ok(())
+}
 
-    writeln!(f, "{GITHUB_CI_PART3}")?;
+/// Write the `publish-release` job: downloads every `build` job's stashed
+/// artifacts, merges their manifest fragments into one top-level
+/// `dist-manifest.json`, and uploads everything to the Release in a single
+/// atomic batch. Only once that's succeeded does it (optionally) flip the
+/// Release out of draft. Shared by the release and nightly workflows.
+fn write_publish_job(f: &mut File, tag_expr: &str, flip_draft: bool, upload_installers: bool) -> io::Result<()> {
+    writeln!(f)?;
+    writeln!(f, "  # Merge per-target artifacts and publish them to the Github Release™️ in one go")?;
+    writeln!(f, "  publish-release:")?;
+    writeln!(f, "    needs: [create-release, build]")?;
+    writeln!(f, "    runs-on: ubuntu-latest")?;
+    writeln!(f, "    env:")?;
+    writeln!(f, "      GH_TOKEN: ${{{{ secrets.GITHUB_TOKEN }}}}")?;
+    writeln!(f, "    steps:")?;
+    writeln!(f, "      - uses: actions/checkout@v3")?;
+    writeln!(f, "      - name: Download all build artifacts")?;
+    writeln!(f, "        uses: actions/download-artifact@v4")?;
+    writeln!(f, "        with:")?;
+    writeln!(f, "          path: fragments")?;
+    writeln!(f, "          pattern: build-*")?;
+    writeln!(f, "          merge-multiple: true")?;
+    writeln!(f, "      - name: Merge manifests and publish")?;
+    writeln!(f, "        run: |")?;
+    writeln!(
+        f,
+        "          # Each fragment's \"releases\" has one entry per package, with only that"
+    )?;
+    writeln!(
+        f,
+        "          # target's artifacts; group by tag_name and union the artifacts together"
+    )?;
+    writeln!(f, "          # so the final manifest has one release entry per package/version.")?;
+    writeln!(f, "          jq -s '")?;
+    writeln!(f, "            [.[].releases[]]")?;
+    writeln!(f, "            | group_by(.tag_name)")?;
+    writeln!(
+        f,
+        "            | map((.[0] | del(.artifacts)) + {{artifacts: (map(.artifacts) | add)}})"
+    )?;
+    writeln!(f, "            | {{releases: .}}")?;
+    writeln!(f, "          ' fragments/manifest-*.json > dist-manifest.json")?;
+    writeln!(f, "          rm fragments/manifest-*.json")?;
+    writeln!(f, "          cat dist-manifest.json")?;
+    if upload_installers {
+        writeln!(
+            f,
+            "          gh release upload {tag_expr} fragments/* dist-manifest.json .github/workflows/install.sh .github/workflows/install.ps1"
+        )?;
+    } else {
+        writeln!(f, "          gh release upload {tag_expr} fragments/* dist-manifest.json")?;
+    }
+    if flip_draft {
+        writeln!(f, "          gh release edit {tag_expr} --draft=false")?;
+    }
 
     Ok(())
 }
 
-fn github_os_for_target(target: &str) -> Option<&'static str> {
+/// How cargo-dist's build step should actually produce a linked binary for a
+/// target, once it's running on its chosen runner.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BuildStrategy {
+    /// The target is the runner's default host triple: plain `cargo build`.
+    Native,
+    /// A different triple than the runner's host, but one its own toolchain
+    /// can still link once the rustup target is installed (e.g. the other
+    /// macOS arch, built via Apple's universal toolchain).
+    NativeCrossSdk,
+    /// A Linux triple the runner's host toolchain can't link (different libc
+    /// and/or arch); build inside a `cross`-provided Docker cross-toolchain.
+    Cross,
+}
+
+/// Which Github-hosted runner a target should build on.
+struct TargetRunner {
+    os: &'static str,
+    strategy: BuildStrategy,
+}
+
+/// Resolve a target triple to the runner it should build on and the strategy
+/// that runner needs to actually link a binary for it, replacing the old
+/// `github_os_for_target`'s "native only" assumption.
+fn resolve_target_runner(target: &str) -> Option<TargetRunner> {
     if target.contains("linux") {
-        Some("ubuntu-latest")
+        let strategy = if target == "x86_64-unknown-linux-gnu" {
+            BuildStrategy::Native
+        } else {
+            // musl libc, and non-x86_64 arches like aarch64/arm, can't be
+            // linked by ubuntu-latest's default toolchain.
+            BuildStrategy::Cross
+        };
+        Some(TargetRunner { os: "ubuntu-latest", strategy })
     } else if target.contains("apple") {
-        Some("macos-latest")
+        // macos-latest runners have been Apple Silicon (arm64) since 2024, so
+        // aarch64-apple-darwin is the native host triple and x86_64-apple-darwin
+        // is the one that needs the extra rustup target installed.
+        let strategy = if target == "x86_64-apple-darwin" {
+            BuildStrategy::NativeCrossSdk
+        } else {
+            BuildStrategy::Native
+        };
+        Some(TargetRunner { os: "macos-latest", strategy })
     } else if target.contains("windows") {
-        Some("windows-latest")
+        Some(TargetRunner {
+            os: "windows-latest",
+            strategy: BuildStrategy::Native,
+        })
     } else {
         None
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn package_validation_case_lists_known_packages() {
+        let mut buf = Vec::new();
+        write_package_validation_case(&mut buf, &["foo".to_owned(), "bar".to_owned()]).unwrap();
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("foo|bar)"));
+        assert!(out.contains("is not a package in this workspace"));
+    }
+
+    #[test]
+    fn package_validation_case_with_no_packages_always_errors() {
+        let mut buf = Vec::new();
+        write_package_validation_case(&mut buf, &[]).unwrap();
+        let out = String::from_utf8(buf).unwrap();
+        // Nothing to match against, so every $PACKAGE_NAME falls straight to the error arm.
+        assert!(!out.contains('|'));
+        assert!(out.contains("is not a package in this workspace"));
+    }
+
+    #[test]
+    fn resolve_target_runner_linux_native_vs_cross() {
+        let native = resolve_target_runner("x86_64-unknown-linux-gnu").unwrap();
+        assert_eq!(native.os, "ubuntu-latest");
+        assert_eq!(native.strategy, BuildStrategy::Native);
+
+        let musl = resolve_target_runner("x86_64-unknown-linux-musl").unwrap();
+        assert_eq!(musl.os, "ubuntu-latest");
+        assert_eq!(musl.strategy, BuildStrategy::Cross);
+
+        let arm = resolve_target_runner("aarch64-unknown-linux-gnu").unwrap();
+        assert_eq!(arm.os, "ubuntu-latest");
+        assert_eq!(arm.strategy, BuildStrategy::Cross);
+    }
+
+    #[test]
+    fn resolve_target_runner_macos_matches_arm64_hosted_runner() {
+        // macos-latest runners have been Apple Silicon since 2024: aarch64 is native,
+        // x86_64 is the one that needs the extra rustup target.
+        let arm = resolve_target_runner("aarch64-apple-darwin").unwrap();
+        assert_eq!(arm.os, "macos-latest");
+        assert_eq!(arm.strategy, BuildStrategy::Native);
+
+        let intel = resolve_target_runner("x86_64-apple-darwin").unwrap();
+        assert_eq!(intel.os, "macos-latest");
+        assert_eq!(intel.strategy, BuildStrategy::NativeCrossSdk);
+    }
+
+    #[test]
+    fn resolve_target_runner_windows_is_native() {
+        let windows = resolve_target_runner("x86_64-pc-windows-msvc").unwrap();
+        assert_eq!(windows.os, "windows-latest");
+        assert_eq!(windows.strategy, BuildStrategy::Native);
+    }
+
+    #[test]
+    fn resolve_target_runner_unknown_target_is_none() {
+        assert!(resolve_target_runner("wasm32-unknown-unknown").is_none());
+    }
+
+    /// A path under the OS temp dir unique to this test, so parallel test
+    /// threads don't collide on the same file.
+    fn temp_path(label: &str) -> Utf8PathBuf {
+        static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+        let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+        let dir = Utf8PathBuf::from_path_buf(std::env::temp_dir()).expect("temp dir is not UTF-8");
+        dir.join(format!("cargo-dist-ci-test-{}-{label}-{n}", std::process::id()))
+    }
+
+    #[test]
+    fn create_autogen_file_allows_first_write() {
+        let path = temp_path("fresh");
+        assert!(create_autogen_file(&path, 1, false).is_ok());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn create_autogen_file_overwrites_when_marker_matches() {
+        let path = temp_path("matching-marker");
+        std::fs::write(&path, format!("{AUTOGEN_MARKER_PREFIX}{:016x}\nold content\n", 42u64)).unwrap();
+        assert!(create_autogen_file(&path, 42, false).is_ok());
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn create_autogen_file_refuses_stale_marker_without_allow_dirty() {
+        let path = temp_path("stale-marker");
+        std::fs::write(&path, format!("{AUTOGEN_MARKER_PREFIX}{:016x}\n", 99u64)).unwrap();
+        let err = create_autogen_file(&path, 1, false).unwrap_err();
+        assert!(err.to_string().contains("different inputs"));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn create_autogen_file_refuses_hand_written_file_without_allow_dirty() {
+        let path = temp_path("hand-written");
+        std::fs::write(&path, "not autogenerated at all\n").unwrap();
+        let err = create_autogen_file(&path, 1, false).unwrap_err();
+        assert!(err.to_string().contains("doesn't look autogenerated"));
+        std::fs::remove_file(&path).ok();
+    }
+
+    #[test]
+    fn create_autogen_file_allow_dirty_overrides_both_refusals() {
+        let path = temp_path("allow-dirty");
+        std::fs::write(&path, "hand edited, no marker at all\n").unwrap();
+        assert!(create_autogen_file(&path, 1, true).is_ok());
+        std::fs::remove_file(&path).ok();
+    }
+}
@@ -0,0 +1,324 @@
+//! Generation of one-line POSIX shell / PowerShell installer scripts.
+//!
+//! These are written next to the generated Github CI workflow at `cargo dist
+//! init`/generate time, and uploaded as Release assets alongside the built
+//! archives. Running them detects the host OS/arch, downloads the matching
+//! archive off the Github Release, verifies its checksum, and unpacks the
+//! binary onto `PATH`.
+
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Write;
+
+use camino::Utf8PathBuf;
+use miette::{IntoDiagnostic, WrapErr};
+use tracing::warn;
+
+/// A target we know how to detect from `uname` (plus a libc check on Linux),
+/// used to build the shell installer's os/arch/libc -> rust-target lookup table.
+struct UnixTarget {
+    target: String,
+    uname_os: &'static str,
+    uname_arch: &'static str,
+    /// glibc/musl discriminator. Only meaningful on Linux, where a triple can
+    /// differ solely in libc (`x86_64-unknown-linux-gnu` vs `-musl`) while
+    /// `uname` reports the same os/arch for both; empty on macOS, which has
+    /// no such split.
+    libc: &'static str,
+}
+
+/// A target we know how to detect from `$env:PROCESSOR_ARCHITECTURE`, used to
+/// build the PowerShell installer's lookup table.
+struct WindowsTarget {
+    target: String,
+    arch: &'static str,
+    /// `msvc` or `gnu`. Unlike libc on Linux, there's no environment signal
+    /// `install.ps1` can check at runtime to tell which one the user wants,
+    /// so at most one ABI per arch survives into the generated table (see
+    /// `dedupe_windows_targets`).
+    abi: &'static str,
+}
+
+pub fn generate_install_scripts(
+    ci_dir: &Utf8PathBuf,
+    targets: &[String],
+) -> Result<(), miette::Report> {
+    let unix_targets = dedupe_unix_targets(targets.iter().filter_map(|t| unix_target(t)).collect());
+    let windows_targets =
+        dedupe_windows_targets(targets.iter().filter_map(|t| windows_target(t)).collect());
+
+    write_install_sh(&ci_dir.join("install.sh"), &unix_targets)?;
+    write_install_ps1(&ci_dir.join("install.ps1"), &windows_targets)?;
+
+    Ok(())
+}
+
+fn unix_target(target: &str) -> Option<UnixTarget> {
+    // `uname -m` reports 64-bit ARM as `aarch64` on Linux but `arm64` on macOS.
+    let uname_arch = if target.starts_with("x86_64") {
+        "x86_64"
+    } else if target.starts_with("aarch64") && target.contains("linux") {
+        "aarch64"
+    } else if target.starts_with("aarch64") {
+        "arm64"
+    } else {
+        return None;
+    };
+    let (uname_os, libc) = if target.contains("linux") {
+        let libc = if target.contains("musl") { "musl" } else { "gnu" };
+        ("Linux", libc)
+    } else if target.contains("apple") {
+        ("Darwin", "")
+    } else {
+        return None;
+    };
+    Some(UnixTarget {
+        target: target.to_owned(),
+        uname_os,
+        uname_arch,
+        libc,
+    })
+}
+
+/// Drop any target whose `(uname_os, uname_arch, libc)` key collides with one
+/// already seen, keeping the first and warning about the rest, so
+/// `write_install_sh` never emits two `case` arms the shell can't tell apart
+/// (which would make the second one permanently dead code).
+fn dedupe_unix_targets(targets: Vec<UnixTarget>) -> Vec<UnixTarget> {
+    let mut seen = HashSet::new();
+    targets
+        .into_iter()
+        .filter(|t| {
+            if seen.insert((t.uname_os, t.uname_arch, t.libc)) {
+                true
+            } else {
+                warn!(
+                    "install.sh can only ship one {}-{}{} binary; skipping {}",
+                    t.uname_os,
+                    t.uname_arch,
+                    if t.libc.is_empty() { String::new() } else { format!("-{}", t.libc) },
+                    t.target
+                );
+                false
+            }
+        })
+        .collect()
+}
+
+fn windows_target(target: &str) -> Option<WindowsTarget> {
+    if !target.contains("windows") {
+        return None;
+    }
+    let arch = if target.starts_with("x86_64") {
+        "AMD64"
+    } else if target.starts_with("aarch64") {
+        "ARM64"
+    } else {
+        return None;
+    };
+    let abi = if target.ends_with("-msvc") {
+        "msvc"
+    } else if target.ends_with("-gnu") {
+        "gnu"
+    } else {
+        return None;
+    };
+    Some(WindowsTarget {
+        target: target.to_owned(),
+        arch,
+        abi,
+    })
+}
+
+/// Keep at most one target per arch, preferring `msvc` over `gnu`: unlike the
+/// Linux libc case, `install.ps1` has no runtime signal to decide which ABI
+/// the user actually wants, so shipping both would just mean whichever comes
+/// first in the `switch` silently wins (every arm `break`s after it matches).
+/// `msvc` is the toolchain `rustup`'s default host uses on
+/// Windows, so it's the better single guess.
+fn dedupe_windows_targets(mut targets: Vec<WindowsTarget>) -> Vec<WindowsTarget> {
+    targets.sort_by_key(|t| t.abi != "msvc");
+    let mut seen = HashSet::new();
+    targets
+        .into_iter()
+        .filter(|t| {
+            if seen.insert(t.arch) {
+                true
+            } else {
+                warn!(
+                    "install.ps1 can only ship one {} binary; skipping {} in favor of the msvc build",
+                    t.arch, t.target
+                );
+                false
+            }
+        })
+        .collect()
+}
+
+fn write_install_sh(path: &Utf8PathBuf, targets: &[UnixTarget]) -> Result<(), miette::Report> {
+    let mut file = File::create(path)
+        .into_diagnostic()
+        .wrap_err("Failed to create install.sh")?;
+
+    writeln!(file, "#!/bin/sh").into_diagnostic()?;
+    writeln!(file, "# Autogenerated by cargo-dist. Detects your OS/arch, downloads the").into_diagnostic()?;
+    writeln!(file, "# matching release archive, verifies its checksum, and installs the").into_diagnostic()?;
+    writeln!(file, "# binary onto PATH.").into_diagnostic()?;
+    writeln!(file, "set -eu").into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(file, "REPO=\"${{CARGO_DIST_REPO:?set CARGO_DIST_REPO to <owner>/<name>}}\"").into_diagnostic()?;
+    writeln!(file, "TAG=\"${{1:-latest}}\"").into_diagnostic()?;
+    writeln!(file, "INSTALL_DIR=\"${{CARGO_DIST_INSTALL_DIR:-$HOME/.cargo/bin}}\"").into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(file, "arch=\"$(uname -m)\"").into_diagnostic()?;
+    writeln!(file, "os=\"$(uname -s)\"").into_diagnostic()?;
+    writeln!(file, "libc=\"\"").into_diagnostic()?;
+    writeln!(file, "if [ \"$os\" = \"Linux\" ]; then").into_diagnostic()?;
+    writeln!(file, "  libc=\"gnu\"").into_diagnostic()?;
+    writeln!(
+        file,
+        "  ldd --version 2>&1 | grep -qi musl && libc=\"musl\""
+    )
+    .into_diagnostic()?;
+    writeln!(file, "fi").into_diagnostic()?;
+    writeln!(file, "case \"$os-$arch-$libc\" in").into_diagnostic()?;
+    for t in targets {
+        writeln!(
+            file,
+            "  {}-{}-{}) target=\"{}\" ;;",
+            t.uname_os, t.uname_arch, t.libc, t.target
+        )
+        .into_diagnostic()?;
+    }
+    writeln!(file, "  *) echo \"unsupported platform: $os $arch\" >&2; exit 1 ;;").into_diagnostic()?;
+    writeln!(file, "esac").into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(file, "if [ \"$TAG\" = \"latest\" ]; then").into_diagnostic()?;
+    writeln!(file, "  api=\"https://api.github.com/repos/$REPO/releases/latest\"").into_diagnostic()?;
+    writeln!(file, "else").into_diagnostic()?;
+    writeln!(file, "  api=\"https://api.github.com/repos/$REPO/releases/tags/$TAG\"").into_diagnostic()?;
+    writeln!(file, "fi").into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(
+        file,
+        "asset=\"$(curl -sSL \"$api\" | grep -o \"\\\"browser_download_url\\\": *\\\"[^\\\"]*$target[^\\\"]*\\\"\" | grep -v '.sha256\"' | head -n1 | cut -d'\"' -f4)\""
+    )
+    .into_diagnostic()?;
+    writeln!(
+        file,
+        "[ -n \"$asset\" ] || {{ echo \"no release asset found for $target\" >&2; exit 1; }}"
+    )
+    .into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(file, "tmp=\"$(mktemp -d)\"").into_diagnostic()?;
+    writeln!(file, "archive=\"$tmp/$(basename \"$asset\")\"").into_diagnostic()?;
+    writeln!(file, "curl -sSL -o \"$archive\" \"$asset\"").into_diagnostic()?;
+    writeln!(file, "curl -sSL -o \"$archive.sha256\" \"$asset.sha256\"").into_diagnostic()?;
+    writeln!(
+        file,
+        "(cd \"$tmp\" && sha256sum -c \"$(basename \"$archive\").sha256\" 2>/dev/null || shasum -a 256 -c \"$(basename \"$archive\").sha256\")"
+    )
+    .into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(file, "tar -xzf \"$archive\" -C \"$tmp\"").into_diagnostic()?;
+    writeln!(
+        file,
+        "bin=\"$(find \"$tmp\" -maxdepth 2 -type f -perm -u+x ! -name '*.sha256' | head -n1)\""
+    )
+    .into_diagnostic()?;
+    writeln!(file, "mkdir -p \"$INSTALL_DIR\"").into_diagnostic()?;
+    writeln!(file, "install -m 755 \"$bin\" \"$INSTALL_DIR/\"").into_diagnostic()?;
+    writeln!(file, "rm -rf \"$tmp\"").into_diagnostic()?;
+    writeln!(file, "echo \"installed to $INSTALL_DIR\"").into_diagnostic()?;
+
+    Ok(())
+}
+
+fn write_install_ps1(path: &Utf8PathBuf, targets: &[WindowsTarget]) -> Result<(), miette::Report> {
+    let mut file = File::create(path)
+        .into_diagnostic()
+        .wrap_err("Failed to create install.ps1")?;
+
+    writeln!(file, "# Autogenerated by cargo-dist. Detects your arch, downloads the").into_diagnostic()?;
+    writeln!(file, "# matching release archive, verifies its checksum, and installs the").into_diagnostic()?;
+    writeln!(file, "# binary onto PATH.").into_diagnostic()?;
+    writeln!(file, "param(").into_diagnostic()?;
+    writeln!(file, "  [string]$Tag = \"latest\"").into_diagnostic()?;
+    writeln!(file, ")").into_diagnostic()?;
+    writeln!(file, "$ErrorActionPreference = \"Stop\"").into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(file, "$Repo = $env:CARGO_DIST_REPO").into_diagnostic()?;
+    writeln!(file, "if (-not $Repo) {{ throw \"set CARGO_DIST_REPO to <owner>/<name>\" }}").into_diagnostic()?;
+    writeln!(
+        file,
+        "$InstallDir = if ($env:CARGO_DIST_INSTALL_DIR) {{ $env:CARGO_DIST_INSTALL_DIR }} else {{ \"$env:USERPROFILE\\.cargo\\bin\" }}"
+    )
+    .into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(file, "switch ($env:PROCESSOR_ARCHITECTURE) {{").into_diagnostic()?;
+    for t in targets {
+        // `break` because `switch` doesn't short-circuit on the first match by
+        // default; without it every matching clause would run and the last one
+        // would silently win.
+        writeln!(
+            file,
+            "  \"{}\" {{ $Target = \"{}\"; break }}",
+            t.arch, t.target
+        )
+        .into_diagnostic()?;
+    }
+    writeln!(
+        file,
+        "  default {{ throw \"unsupported arch: $env:PROCESSOR_ARCHITECTURE\" }}"
+    )
+    .into_diagnostic()?;
+    writeln!(file, "}}").into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(
+        file,
+        "$Api = if ($Tag -eq \"latest\") {{ \"https://api.github.com/repos/$Repo/releases/latest\" }} else {{ \"https://api.github.com/repos/$Repo/releases/tags/$Tag\" }}"
+    )
+    .into_diagnostic()?;
+    writeln!(file, "$Release = Invoke-RestMethod -Uri $Api").into_diagnostic()?;
+    writeln!(
+        file,
+        "$Asset = $Release.assets | Where-Object {{ $_.name -like \"*$Target*\" -and $_.name -notlike \"*.sha256\" }} | Select-Object -First 1"
+    )
+    .into_diagnostic()?;
+    writeln!(file, "if (-not $Asset) {{ throw \"no release asset found for $Target\" }}").into_diagnostic()?;
+    writeln!(
+        file,
+        "$Sha256Asset = $Release.assets | Where-Object {{ $_.name -eq \"$($Asset.name).sha256\" }} | Select-Object -First 1"
+    )
+    .into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(file, "$Tmp = Join-Path $env:TEMP ([System.Guid]::NewGuid())").into_diagnostic()?;
+    writeln!(file, "New-Item -ItemType Directory -Path $Tmp | Out-Null").into_diagnostic()?;
+    writeln!(file, "$Archive = Join-Path $Tmp $Asset.name").into_diagnostic()?;
+    writeln!(file, "Invoke-WebRequest -Uri $Asset.browser_download_url -OutFile $Archive").into_diagnostic()?;
+    writeln!(file, "if (-not $Sha256Asset) {{ throw \"no checksum found for $($Asset.name)\" }}").into_diagnostic()?;
+    writeln!(
+        file,
+        "$ExpectedHash = (Invoke-WebRequest -Uri $Sha256Asset.browser_download_url).Content.Split(\" \")[0].Trim()"
+    )
+    .into_diagnostic()?;
+    writeln!(file, "$ActualHash = (Get-FileHash -Path $Archive -Algorithm SHA256).Hash").into_diagnostic()?;
+    writeln!(
+        file,
+        "if ($ActualHash.ToLower() -ne $ExpectedHash.ToLower()) {{ throw \"checksum mismatch for $($Asset.name)\" }}"
+    )
+    .into_diagnostic()?;
+    writeln!(file).into_diagnostic()?;
+    writeln!(file, "Expand-Archive -Path $Archive -DestinationPath $Tmp -Force").into_diagnostic()?;
+    writeln!(
+        file,
+        "$Bin = Get-ChildItem -Path $Tmp -Recurse -Filter *.exe | Select-Object -First 1"
+    )
+    .into_diagnostic()?;
+    writeln!(file, "New-Item -ItemType Directory -Path $InstallDir -Force | Out-Null").into_diagnostic()?;
+    writeln!(file, "Copy-Item -Path $Bin.FullName -Destination $InstallDir -Force").into_diagnostic()?;
+    writeln!(file, "Remove-Item -Path $Tmp -Recurse -Force").into_diagnostic()?;
+    writeln!(file, "Write-Host \"installed to $InstallDir\"").into_diagnostic()?;
+
+    Ok(())
+}